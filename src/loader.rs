@@ -0,0 +1,83 @@
+//! Walks a directory of knowledge documents and extracts plain text from
+//! each one, dispatching on file extension so a folder can mix PDFs with
+//! plain-text notes instead of Perso being locked to a single PDF.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Raw text extracted from one source file, tagged with the filename it
+/// came from so chunks built from it can cite where they were retrieved
+/// from.
+pub struct Document {
+    pub source: String,
+    pub content: String,
+}
+
+/// Walks `dir` and extracts text from every file with a supported
+/// extension. Files with unrecognized extensions are skipped rather than
+/// treated as an error, since a knowledge folder may hold other files too.
+pub fn load_documents(dir: &Path) -> Result<Vec<Document>> {
+    if !dir.exists() {
+        anyhow::bail!(
+            "Directory '{}' not found! Please place your knowledge files there.",
+            dir.display()
+        );
+    }
+
+    let mut paths = Vec::new();
+    collect_files(dir, &mut paths)?;
+    paths.sort();
+
+    let mut documents = Vec::new();
+
+    for path in paths {
+        let Some(content) = load_file(&path)? else {
+            continue;
+        };
+
+        let source = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+
+        documents.push(Document { source, content });
+    }
+
+    Ok(documents)
+}
+
+/// Recursively collects every file under `dir` into `out`, so a knowledge
+/// folder can be organized into subdirectories instead of everything living
+/// flat at the top level.
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else if path.is_file() {
+            out.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts text from a single file based on its extension, or returns
+/// `None` if the extension isn't one Perso knows how to ingest.
+fn load_file(path: &Path) -> Result<Option<String>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pdf") => pdf_extract::extract_text(path)
+            .with_context(|| format!("Failed to extract text from {}", path.display()))
+            .map(Some),
+        Some("txt") | Some("md") => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .map(Some),
+        _ => Ok(None),
+    }
+}
@@ -1,94 +1,316 @@
+mod loader;
+mod store;
+
 use anyhow::{Context, Result};
+use loader::Document;
 use rig::client::{CompletionClient, Nothing};
-use rig::completion::Prompt;
-use rig::embeddings::EmbeddingsBuilder;
+use rig::completion::{Chat, Message};
+use rig::embeddings::{Embed, EmbedError, EmbeddingModel as _, TextEmbedder};
 use rig::providers::ollama::{self, EmbeddingModel};
-use rig::vector_store::in_memory_store::InMemoryVectorStore;
+use rig::vector_store::request::VectorSearchRequest;
+use rig::vector_store::VectorStoreIndexDyn;
+use serde::{Deserialize, Serialize};
 use std::io::{self, Write};
 use std::path::Path;
+use store::{StoreConfig, VectorIndex};
+
+const KNOWLEDGE_DIR: &str = "knowledge";
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_EMBEDDING_MODEL: &str = ollama::NOMIC_EMBED_TEXT;
+const DEFAULT_LLM_MODEL: &str = "llama3:latest";
+const DEFAULT_TOP_K_RESULTS: usize = 3; // Increased from 2 for better context
+
+// Keeps the prompt sent to the model bounded as a conversation grows; the
+// oldest user/assistant pair is dropped once more than this many turns
+// accumulate.
+const MAX_HISTORY_TURNS: usize = 10;
+
+// Passage size and overlap, counted in chars rather than tokens so no
+// tokenizer is needed; at several chars per token of English text, a
+// 1000-char passage stays well under an embedding model's max token limit.
+const CHUNK_SIZE_CHARS: usize = 1000;
+const CHUNK_OVERLAP_CHARS: usize = 200;
+
+/// Runtime knobs read from the environment, falling back to the defaults
+/// above so Perso runs out of the box against a local Ollama instance.
+struct Config {
+    ollama_url: String,
+    llm_model: String,
+    embed_model: String,
+    top_k: usize,
+    /// Minimum similarity score a retrieved passage must clear to be used as
+    /// context. `None` means every top-k result is accepted, same as before
+    /// this was configurable.
+    min_score: Option<f64>,
+    /// Whether retrieved passages and their scores are printed alongside each
+    /// answer. Can also be toggled mid-session with the `/sources` command.
+    show_sources: bool,
+}
 
-const PDF_PATH: &str = "knowledge.pdf";
-const EMBEDDING_MODEL: &str = ollama::NOMIC_EMBED_TEXT;
-const EMBEDDING_DIMS: usize = 768;
-const LLM_MODEL: &str = "llama3:latest";
-const TOP_K_RESULTS: usize = 3; // Increased from 2 for better context
+impl Config {
+    fn from_env() -> Self {
+        Self {
+            ollama_url: std::env::var("PERSO_OLLAMA_URL")
+                .unwrap_or_else(|_| DEFAULT_OLLAMA_URL.to_string()),
+            llm_model: std::env::var("PERSO_LLM_MODEL")
+                .unwrap_or_else(|_| DEFAULT_LLM_MODEL.to_string()),
+            embed_model: std::env::var("PERSO_EMBED_MODEL")
+                .unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string()),
+            top_k: std::env::var("PERSO_TOP_K")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_TOP_K_RESULTS),
+            min_score: std::env::var("PERSO_MIN_SCORE")
+                .ok()
+                .and_then(|value| value.parse().ok()),
+            show_sources: std::env::var("PERSO_SHOW_SOURCES")
+                .ok()
+                .is_some_and(|value| value == "1" || value.eq_ignore_ascii_case("true")),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let config = Config::from_env();
+
     // 1. Initialize Ollama Client
-    let client: rig::client::Client<ollama::OllamaExt> = create_ollama_client()?;
+    let client: rig::client::Client<ollama::OllamaExt> = create_ollama_client(&config.ollama_url)?;
 
-    // 2. Load and process PDF
-    println!("📖 Reading {}...", PDF_PATH);
-    let content: String = load_pdf_content(PDF_PATH)?;
+    // 2. Load and process the knowledge folder
+    println!("📖 Reading documents from {}/...", KNOWLEDGE_DIR);
+    let documents: Vec<Document> = loader::load_documents(Path::new(KNOWLEDGE_DIR))?;
 
     // 3. Create embeddings and vector store
     println!("🔨 Creating embeddings...");
-    let embedding_model: EmbeddingModel = create_embedding_model(&client);
-    let vector_store: InMemoryVectorStore<String> =
-        build_vector_store(content, &embedding_model).await?;
+    let embedding_model: EmbeddingModel =
+        create_embedding_model(&client, &config.embed_model).await?;
+    let source_hash = store::content_hash(&combined_content(&documents));
+    let passages: Vec<Passage> = documents
+        .iter()
+        .flat_map(|doc| chunk_content(&doc.source, &doc.content, CHUNK_SIZE_CHARS, CHUNK_OVERLAP_CHARS))
+        .collect();
+    let store_config = StoreConfig::from_env();
+    let vector_index = store::build_index(
+        &store_config,
+        &source_hash,
+        &config.embed_model,
+        passages,
+        embedding_model,
+    )
+    .await?;
 
     // 4. Create RAG agent
+    //
+    // Retrieval is performed explicitly in the chat loop below (rather than
+    // via `.dynamic_context`) so the retrieved passages and their scores can
+    // be surfaced to the user instead of being hidden inside the agent.
     let agent: rig::agent::Agent<ollama::CompletionModel> = client
-        .agent(LLM_MODEL)
+        .agent(&config.llm_model)
         .preamble(
             "You are 'Perso', a knowledgeable personal assistant. \
              Answer questions accurately based on the provided context. \
              If the context doesn't contain relevant information, say so honestly.",
         )
-        .dynamic_context(TOP_K_RESULTS, vector_store.index(embedding_model))
         .build();
 
     // 5. Interactive chat loop
-    run_chat_loop(agent).await?;
+    run_chat_loop(agent, vector_index, &config).await?;
 
     Ok(())
 }
 
-fn create_ollama_client() -> Result<ollama::Client> {
+fn create_ollama_client(base_url: &str) -> Result<ollama::Client> {
     ollama::Client::builder()
         .api_key(Nothing)
+        .base_url(base_url)
         .build()
         .context("Failed to create Ollama client")
 }
 
-fn load_pdf_content(path: &str) -> Result<String> {
-    let pdf_path: &Path = Path::new(path);
+/// Concatenates every document's source name and content into one string so
+/// the whole knowledge folder can be hashed with a single call; changing,
+/// adding, or removing any file changes the hash. Each field is wrapped in
+/// `\0` rather than just separated by it, so bytes can never shift across a
+/// document boundary into another document's identical combined string.
+fn combined_content(documents: &[Document]) -> String {
+    documents
+        .iter()
+        .map(|doc| format!("\0{}\0{}\0", doc.source, doc.content))
+        .collect()
+}
+
+async fn create_embedding_model(client: &ollama::Client, model: &str) -> Result<EmbeddingModel> {
+    let dims = probe_embedding_dims(client, model).await?;
+    Ok(EmbeddingModel::new(client.clone(), model, dims))
+}
+
+/// Probes `model`'s embedding width by embedding a throwaway string.
+async fn probe_embedding_dims(client: &ollama::Client, model: &str) -> Result<usize> {
+    let probe = EmbeddingModel::new(client.clone(), model, 0);
+
+    let embedding = probe.embed_text("test").await.map_err(|err| {
+        if model_not_found(&err) {
+            anyhow::anyhow!(
+                "Embedding model '{model}' was not found on the Ollama instance. \
+                 Pull it first with `ollama pull {model}`."
+            )
+        } else {
+            anyhow::Error::new(err)
+                .context("Failed to reach Ollama while probing embedding dimensions")
+        }
+    })?;
+
+    Ok(embedding.vec.len())
+}
+
+fn model_not_found(err: &rig::embeddings::EmbeddingError) -> bool {
+    matches!(
+        err,
+        rig::embeddings::EmbeddingError::ProviderError(msg) if msg.contains("not found")
+    )
+}
+
+/// A passage retrieved for a query, together with its similarity score (1.0
+/// is a perfect match).
+type ScoredPassage = (f64, Passage);
+
+/// Runs an explicit top-k vector query for `query`. `min_score` is passed to
+/// the backend as a hint, then re-applied here since some backends ignore it.
+async fn retrieve(
+    index: &VectorIndex,
+    query: &str,
+    top_k: usize,
+    min_score: Option<f64>,
+) -> Result<Vec<ScoredPassage>> {
+    let mut builder = VectorSearchRequest::builder()
+        .query(query)
+        .samples(top_k as u64);
+    if let Some(min_score) = min_score {
+        builder = builder.threshold(min_score);
+    }
+    let request = builder
+        .build()
+        .context("Failed to build vector search request")?;
+
+    let results = index
+        .top_n(request)
+        .await
+        .context("Failed to query vector store")?;
+
+    results
+        .into_iter()
+        .map(|(score, _id, value)| {
+            serde_json::from_value(value)
+                .map(|passage| (score, passage))
+                .context("Failed to decode a retrieved passage")
+        })
+        .filter(|result| match result {
+            Ok((score, _)) => min_score.is_none_or(|min| *score >= min),
+            Err(_) => true,
+        })
+        .collect()
+}
+
+/// Prints each retrieved passage's source location and similarity score.
+fn print_sources(passages: &[ScoredPassage]) {
+    if passages.is_empty() {
+        println!("📚 No passages cleared the relevance bar.\n");
+        return;
+    }
 
-    if !pdf_path.exists() {
-        anyhow::bail!(
-            "File '{}' not found! Please place it in the project folder.",
-            path
+    println!("📚 Sources:");
+    for (score, passage) in passages {
+        println!(
+            "   [{:.3}] {} (offset {})",
+            score, passage.source, passage.offset
         );
     }
+    println!();
+}
+
+/// Builds the prompt sent to the model, folding the retrieved passages in as
+/// context ahead of the user's question.
+fn build_prompt(query: &str, passages: &[ScoredPassage]) -> String {
+    if passages.is_empty() {
+        return query.to_string();
+    }
 
-    pdf_extract::extract_text(pdf_path).context("Failed to extract text from PDF")
+    let context = passages
+        .iter()
+        .map(|(_, passage)| format!("[{}] {}", passage.source, passage.text))
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+
+    format!("Context:\n{context}\n\nQuestion: {query}")
 }
 
-fn create_embedding_model(client: &ollama::Client) -> EmbeddingModel {
-    EmbeddingModel::new(client.clone(), EMBEDDING_MODEL, EMBEDDING_DIMS)
+/// A passage is a single chunk of a source document, small enough to embed
+/// without truncation and specific enough that top-k retrieval returns
+/// genuinely distinct context instead of one giant blob. `source` and
+/// `offset` let retrieval results cite exactly which file a passage came
+/// from and roughly where in it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Passage {
+    pub(crate) id: String,
+    pub(crate) source: String,
+    pub(crate) offset: usize,
+    pub(crate) text: String,
 }
 
-async fn build_vector_store(
-    content: String,
-    embedding_model: &EmbeddingModel,
-) -> Result<InMemoryVectorStore<String>> {
-    let embeddings: Vec<(String, rig::OneOrMany<rig::embeddings::Embedding>)> =
-        EmbeddingsBuilder::new(embedding_model.clone())
-            .document(content)
-            .context("Failed to create document embedding")?
-            .build()
-            .await
-            .context("Failed to build embeddings")?;
+impl Embed for Passage {
+    fn embed(&self, embedder: &mut TextEmbedder) -> Result<(), EmbedError> {
+        embedder.embed(self.text.clone());
+        Ok(())
+    }
+}
 
-    Ok(InMemoryVectorStore::from_documents(embeddings))
+/// Splits `content` (extracted from `source`) into overlapping passages of
+/// roughly `chunk_size` chars, each carrying `overlap` chars of the previous
+/// passage so that context spanning a chunk boundary isn't lost to
+/// retrieval. Each passage records its char offset into `content` as a
+/// stand-in for a page number, since plain-text extraction doesn't preserve
+/// page boundaries.
+fn chunk_content(source: &str, content: &str, chunk_size: usize, overlap: usize) -> Vec<Passage> {
+    let chars: Vec<char> = content.chars().collect();
+    if chars.is_empty() {
+        return Vec::new();
+    }
+
+    let stride = chunk_size.saturating_sub(overlap).max(1);
+    let mut passages = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        let end = (start + chunk_size).min(chars.len());
+        let text: String = chars[start..end].iter().collect();
+
+        passages.push(Passage {
+            id: format!("{source}#chunk{}", passages.len()),
+            source: source.to_string(),
+            offset: start,
+            text,
+        });
+
+        if end == chars.len() {
+            break;
+        }
+        start += stride;
+    }
+
+    passages
 }
 
-async fn run_chat_loop(agent: impl Prompt) -> Result<()> {
-    println!("✨ Perso is ready! (Type 'exit' or 'quit' to end)\n");
+async fn run_chat_loop(agent: impl Chat, vector_index: VectorIndex, config: &Config) -> Result<()> {
+    println!(
+        "✨ Perso is ready! (Type 'exit'/'quit' to end, 'reset'/'clear' to forget history, \
+         '/sources' to toggle showing retrieved passages)\n"
+    );
 
     let stdin: io::Stdin = io::stdin();
     let mut stdout: io::Stdout = io::stdout();
+    let mut history: Vec<Message> = Vec::new();
+    let mut show_sources = config.show_sources;
 
     loop {
         print!("👤 You: ");
@@ -106,11 +328,51 @@ async fn run_chat_loop(agent: impl Prompt) -> Result<()> {
                 println!("👋 Goodbye!");
                 break;
             }
+            "reset" | "clear" => {
+                history.clear();
+                println!("🧹 Conversation history cleared.\n");
+            }
+            "/sources" => {
+                show_sources = !show_sources;
+                println!(
+                    "🔎 Source display {}.\n",
+                    if show_sources { "enabled" } else { "disabled" }
+                );
+            }
             "" => continue,
             _ => {
+                let passages =
+                    match retrieve(&vector_index, query, config.top_k, config.min_score).await {
+                        Ok(passages) => passages,
+                        Err(e) => {
+                            eprintln!("❌ Error: {}\n", e);
+                            continue;
+                        }
+                    };
+
+                if show_sources {
+                    print_sources(&passages);
+                }
+
+                if passages.is_empty() && config.min_score.is_some() {
+                    println!(
+                        "🤖 Perso: I don't have any relevant context for that, so I won't guess.\n"
+                    );
+                    continue;
+                }
+
                 println!("🤖 Perso thinking...");
-                match agent.prompt(query).await {
-                    Ok(response) => println!("🤖 Perso: {}\n", response),
+                // `chat` returns the full reply in one shot, so the history
+                // below is only ever appended to with what the model
+                // actually said, never with a partial answer fed back in.
+                let prompt = build_prompt(query, &passages);
+                match agent.chat(prompt, history.clone()).await {
+                    Ok(response) => {
+                        println!("🤖 Perso: {}\n", response);
+                        history.push(Message::user(query));
+                        history.push(Message::assistant(response));
+                        evict_oldest_turns(&mut history, MAX_HISTORY_TURNS);
+                    }
                     Err(e) => eprintln!("❌ Error: {}\n", e),
                 }
             }
@@ -119,3 +381,135 @@ async fn run_chat_loop(agent: impl Prompt) -> Result<()> {
 
     Ok(())
 }
+
+/// Drops the oldest user/assistant pairs once `history` holds more than
+/// `max_turns` turns, keeping the prompt sent to the model bounded as a
+/// conversation grows.
+fn evict_oldest_turns(history: &mut Vec<Message>, max_turns: usize) {
+    let max_messages = max_turns * 2;
+    if history.len() > max_messages {
+        history.drain(0..history.len() - max_messages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_content_empty_input_yields_no_passages() {
+        assert_eq!(chunk_content("doc.txt", "", 100, 20), Vec::new());
+    }
+
+    #[test]
+    fn chunk_content_overlap_at_least_chunk_size_still_advances() {
+        // `overlap >= chunk_size` would zero out the stride; the `.max(1)`
+        // fallback in `chunk_content` must still make forward progress.
+        let content = "abcdefghij";
+        let passages = chunk_content("doc.txt", content, 4, 4);
+
+        assert!(passages.len() > 1);
+        let offsets: Vec<usize> = passages.iter().map(|p| p.offset).collect();
+        assert!(offsets.windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[test]
+    fn chunk_content_exact_boundary_produces_one_passage() {
+        let content = "abcd";
+        let passages = chunk_content("doc.txt", content, 4, 1);
+
+        assert_eq!(passages.len(), 1);
+        assert_eq!(passages[0].text, "abcd");
+        assert_eq!(passages[0].offset, 0);
+    }
+
+    #[test]
+    fn chunk_content_overlapping_chunks_share_a_tail() {
+        let content = "abcdefghij";
+        let passages = chunk_content("doc.txt", content, 4, 2);
+
+        assert_eq!(passages[0].text, "abcd");
+        assert_eq!(passages[1].text, "cdef");
+        assert_eq!(passages[1].offset, 2);
+    }
+
+    fn turn(text: &str) -> Message {
+        Message::user(text)
+    }
+
+    #[test]
+    fn evict_oldest_turns_keeps_history_under_the_cap() {
+        let mut history: Vec<Message> = (0..10).map(|i| turn(&i.to_string())).collect();
+        evict_oldest_turns(&mut history, 2);
+        assert_eq!(history.len(), 4);
+    }
+
+    #[test]
+    fn evict_oldest_turns_drops_whole_pairs_not_single_messages() {
+        let mut history: Vec<Message> = (0..6).map(|i| turn(&i.to_string())).collect();
+        evict_oldest_turns(&mut history, 2);
+        // The chat loop always appends user/assistant pairs, so history has
+        // an even length and draining down to `max_turns * 2` messages
+        // removes a multiple of two; a pair is never split.
+        assert_eq!(history.len() % 2, 0);
+    }
+
+    #[test]
+    fn evict_oldest_turns_leaves_short_history_untouched() {
+        let mut history: Vec<Message> = (0..3).map(|i| turn(&i.to_string())).collect();
+        evict_oldest_turns(&mut history, 5);
+        assert_eq!(history.len(), 3);
+    }
+
+    fn document(source: &str, content: &str) -> Document {
+        Document {
+            source: source.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn combined_content_is_empty_for_no_documents() {
+        assert_eq!(combined_content(&[]), "");
+    }
+
+    #[test]
+    fn combined_content_changes_when_a_file_is_added() {
+        let before = combined_content(&[document("a.txt", "hello")]);
+        let after = combined_content(&[document("a.txt", "hello"), document("b.txt", "world")]);
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn combined_content_does_not_collide_across_boundaries() {
+        // Without a separator wrapping each field, splitting "ab" + "cd" into
+        // "a" + "bcd" would hash identically; the `\0` wrapper must prevent
+        // that.
+        let split_early = combined_content(&[document("a", "bcd")]);
+        let split_late = combined_content(&[document("ab", "cd")]);
+        assert_ne!(split_early, split_late);
+    }
+
+    fn passage(source: &str, text: &str) -> Passage {
+        Passage {
+            id: format!("{source}#0"),
+            source: source.to_string(),
+            offset: 0,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn build_prompt_with_no_passages_returns_the_bare_query() {
+        assert_eq!(build_prompt("what is perso?", &[]), "what is perso?");
+    }
+
+    #[test]
+    fn build_prompt_folds_passages_in_ahead_of_the_question() {
+        let passages = vec![(0.9, passage("notes.md", "Perso is a RAG assistant."))];
+        let prompt = build_prompt("what is perso?", &passages);
+
+        assert!(prompt.contains("Perso is a RAG assistant."));
+        assert!(prompt.ends_with("Question: what is perso?"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Vector store backends for Perso's retrieval index: a content-hash-keyed
+//! on-disk cache, and an optional Qdrant-backed collection. Both are exposed
+//! through [`VectorIndex`], which implements
+//! [`rig::vector_store::VectorStoreIndexDyn`].
+
+use anyhow::{Context, Result};
+use qdrant_client::qdrant::{
+    CreateCollectionBuilder, Distance, QueryPointsBuilder, VectorParamsBuilder,
+};
+use qdrant_client::Qdrant;
+use rig::embeddings::{EmbeddingModel as _, EmbeddingsBuilder};
+use rig::providers::ollama::EmbeddingModel;
+use rig::vector_store::in_memory_store::{InMemoryVectorIndex, InMemoryVectorStore};
+use rig::vector_store::request::{Filter, VectorSearchRequest};
+use rig::vector_store::{InsertDocuments, TopNResults, VectorStoreError, VectorStoreIndexDyn};
+use rig::wasm_compat::WasmBoxedFuture;
+use rig_qdrant::QdrantVectorStore;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::Passage;
+
+const CACHE_DIR: &str = ".perso_cache";
+
+/// Where to keep the index: in this process's memory (rebuilt from the disk
+/// cache when possible) or in an external Qdrant collection.
+pub enum StoreConfig {
+    InMemory,
+    /// `collection` is a base name; see `versioned_collection` for how the
+    /// actual collection name is derived from it.
+    Qdrant { url: String, collection: String },
+}
+
+impl StoreConfig {
+    /// Reads backend choice from the environment. Set `PERSO_QDRANT_URL` (and
+    /// optionally `PERSO_QDRANT_COLLECTION`) to opt into a durable Qdrant
+    /// collection; otherwise Perso keeps everything in memory.
+    pub fn from_env() -> Self {
+        match std::env::var("PERSO_QDRANT_URL") {
+            Ok(url) => {
+                let collection = std::env::var("PERSO_QDRANT_COLLECTION")
+                    .unwrap_or_else(|_| "perso".to_string());
+                StoreConfig::Qdrant { url, collection }
+            }
+            Err(_) => StoreConfig::InMemory,
+        }
+    }
+}
+
+/// A query-able index backed by either an in-memory store or Qdrant.
+pub enum VectorIndex {
+    InMemory(InMemoryVectorIndex<EmbeddingModel, Passage>),
+    Qdrant(Box<QdrantVectorStore<EmbeddingModel>>),
+}
+
+impl VectorStoreIndexDyn for VectorIndex {
+    fn top_n<'a>(
+        &'a self,
+        req: VectorSearchRequest<Filter<serde_json::Value>>,
+    ) -> WasmBoxedFuture<'a, TopNResults> {
+        match self {
+            VectorIndex::InMemory(index) => index.top_n(req),
+            VectorIndex::Qdrant(index) => index.top_n(req),
+        }
+    }
+
+    fn top_n_ids<'a>(
+        &'a self,
+        req: VectorSearchRequest<Filter<serde_json::Value>>,
+    ) -> WasmBoxedFuture<'a, Result<Vec<(f64, String)>, VectorStoreError>> {
+        match self {
+            VectorIndex::InMemory(index) => index.top_n_ids(req),
+            VectorIndex::Qdrant(index) => index.top_n_ids(req),
+        }
+    }
+}
+
+/// Builds (or loads) the retrieval index for `passages`, honouring `config`.
+/// `source_hash` and `embed_model` together key the on-disk cache and the
+/// Qdrant collection, so either one changing invalidates stale embeddings.
+pub async fn build_index(
+    config: &StoreConfig,
+    source_hash: &str,
+    embed_model: &str,
+    passages: Vec<Passage>,
+    embedding_model: EmbeddingModel,
+) -> Result<VectorIndex> {
+    match config {
+        StoreConfig::InMemory => {
+            let store = load_or_embed(source_hash, embed_model, passages, &embedding_model).await?;
+            Ok(VectorIndex::InMemory(store.index(embedding_model)))
+        }
+        StoreConfig::Qdrant { url, collection } => {
+            let store = load_or_embed_qdrant(
+                url,
+                collection,
+                source_hash,
+                embed_model,
+                passages,
+                embedding_model,
+            )
+            .await?;
+            Ok(VectorIndex::Qdrant(store))
+        }
+    }
+}
+
+type CachedEmbeddings = Vec<(Passage, rig::OneOrMany<rig::embeddings::Embedding>)>;
+
+fn cache_path(source_hash: &str, embed_model: &str) -> PathBuf {
+    Path::new(CACHE_DIR).join(format!("{source_hash}-{embed_model}.json"))
+}
+
+/// Loads previously embedded passages from `.perso_cache/<hash>-<model>.json`
+/// if present, otherwise embeds `passages` and writes the cache for next
+/// time.
+async fn load_or_embed(
+    source_hash: &str,
+    embed_model: &str,
+    passages: Vec<Passage>,
+    embedding_model: &EmbeddingModel,
+) -> Result<InMemoryVectorStore<Passage>> {
+    let path = cache_path(source_hash, embed_model);
+
+    if let Some(cached) = read_cache(&path)? {
+        return Ok(InMemoryVectorStore::from_documents_with_id_f(
+            cached,
+            |passage| passage.id.clone(),
+        ));
+    }
+
+    let embeddings: CachedEmbeddings = EmbeddingsBuilder::new(embedding_model.clone())
+        .documents(passages)
+        .context("Failed to create passage embeddings")?
+        .build()
+        .await
+        .context("Failed to build embeddings")?;
+
+    write_cache(&path, &embeddings)?;
+
+    Ok(InMemoryVectorStore::from_documents_with_id_f(
+        embeddings,
+        |passage| passage.id.clone(),
+    ))
+}
+
+fn read_cache(path: &Path) -> Result<Option<CachedEmbeddings>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read embedding cache at {}", path.display()))?;
+    let cached = serde_json::from_str(&raw)
+        .with_context(|| format!("Failed to parse embedding cache at {}", path.display()))?;
+
+    Ok(Some(cached))
+}
+
+fn write_cache(path: &Path, embeddings: &CachedEmbeddings) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+    }
+
+    let json = serde_json::to_string(embeddings).context("Failed to serialize embeddings")?;
+    std::fs::write(path, json)
+        .with_context(|| format!("Failed to write embedding cache to {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Derives the actual Qdrant collection name from the base `collection`,
+/// `source_hash`, and `embed_model`.
+fn versioned_collection(collection: &str, source_hash: &str, embed_model: &str) -> String {
+    format!("{collection}__{source_hash}-{embed_model}")
+}
+
+/// Loads (or creates and fills) the Qdrant collection for `source_hash` and
+/// `embed_model`.
+async fn load_or_embed_qdrant(
+    url: &str,
+    collection: &str,
+    source_hash: &str,
+    embed_model: &str,
+    passages: Vec<Passage>,
+    embedding_model: EmbeddingModel,
+) -> Result<Box<QdrantVectorStore<EmbeddingModel>>> {
+    let collection = versioned_collection(collection, source_hash, embed_model);
+
+    let client = Qdrant::from_url(url)
+        .build()
+        .with_context(|| format!("Failed to connect to Qdrant at {url}"))?;
+
+    let exists = client
+        .collection_exists(&collection)
+        .await
+        .with_context(|| format!("Failed to check Qdrant collection '{collection}'"))?;
+
+    let dims = embedding_model.ndims() as u64;
+    let query_params = QueryPointsBuilder::new(collection.as_str()).build();
+
+    if exists {
+        return Ok(Box::new(QdrantVectorStore::new(client, embedding_model, query_params)));
+    }
+
+    client
+        .create_collection(
+            CreateCollectionBuilder::new(collection.as_str())
+                .vectors_config(VectorParamsBuilder::new(dims, Distance::Cosine)),
+        )
+        .await
+        .with_context(|| format!("Failed to create Qdrant collection '{collection}'"))?;
+
+    let embeddings: CachedEmbeddings = EmbeddingsBuilder::new(embedding_model.clone())
+        .documents(passages)
+        .context("Failed to create passage embeddings")?
+        .build()
+        .await
+        .context("Failed to build embeddings")?;
+
+    let store = Box::new(QdrantVectorStore::new(client, embedding_model, query_params));
+    store
+        .insert_documents(embeddings)
+        .await
+        .with_context(|| format!("Failed to populate Qdrant collection '{collection}'"))?;
+
+    Ok(store)
+}
+
+/// Hashes `content` so callers can tell whether a cached index is still
+/// valid for the current knowledge documents.
+pub fn content_hash(content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash_is_deterministic() {
+        assert_eq!(content_hash("hello"), content_hash("hello"));
+    }
+
+    #[test]
+    fn content_hash_differs_for_different_content() {
+        assert_ne!(content_hash("hello"), content_hash("world"));
+    }
+
+    #[test]
+    fn content_hash_of_empty_string_is_stable() {
+        assert_eq!(content_hash(""), content_hash(""));
+    }
+
+    #[test]
+    fn versioned_collection_differs_per_model() {
+        let a = versioned_collection("perso", "deadbeef", "nomic-embed-text");
+        let b = versioned_collection("perso", "deadbeef", "mxbai-embed-large");
+        assert_ne!(a, b);
+    }
+}